@@ -1,37 +1,198 @@
 #[macro_use]
 extern crate log;
 extern crate simplelog;
+mod metrics;
+mod notifier;
+mod status;
+mod store;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use log::SetLoggerError;
+use metrics::Metrics;
+use notifier::{GraphNotifier, Notifier, WebhookNotifier};
 use serde::{Deserialize, Serialize};
 use serde_json;
+use sha2::{Digest, Sha256};
 use simplelog::*;
 use sqlx::{migrate::MigrateDatabase, Sqlite, SqlitePool};
+use status::{AppState, RunStatus};
+use std::fmt;
 use std::fs::File;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use store::{FileStore, ObjectStore, Store};
+use tokio::sync::RwLock;
+
+/// Safety buffer (in seconds) subtracted from a token's reported lifetime so we
+/// refresh slightly before Microsoft actually expires it.
+const TOKEN_EXPIRY_BUFFER_SECS: i64 = 60;
+
+/// Default for `AppConfig::max_send_attempts` when not set in config.json.
+const DEFAULT_MAX_SEND_ATTEMPTS: i32 = 5;
+/// Base delay for the exponential backoff applied between retries.
+const SEND_RETRY_BASE_SECS: i64 = 30;
+/// Upper bound on the backoff delay between retries.
+const SEND_RETRY_MAX_SECS: i64 = 3600;
+
+const CONFIG_PATH: &str = "config.json";
+const DEFAULT_DB_URL: &str = "sqlite://sqlite.db";
+const DEFAULT_SECRETS_PATH: &str = "secrets.json";
+const DEFAULT_CREDENTIALS_PATH: &str = "credentials.json";
+
+fn default_db_url() -> String {
+    DEFAULT_DB_URL.to_string()
+}
+
+fn default_secrets_path() -> String {
+    DEFAULT_SECRETS_PATH.to_string()
+}
+
+fn default_credentials_path() -> String {
+    DEFAULT_CREDENTIALS_PATH.to_string()
+}
+
+fn default_poll_interval_secs() -> u64 {
+    300
+}
+
+fn default_status_addr() -> String {
+    "0.0.0.0:8080".to_string()
+}
+
+fn default_max_send_attempts() -> i32 {
+    DEFAULT_MAX_SEND_ATTEMPTS
+}
+
+/// Which [`Notifier`] backend(s) to deliver announcements through, selected
+/// from config so a site can run Graph, a webhook, or both for a target.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum NotifierConfig {
+    Graph { chat_id: String, card_title: String },
+    Webhook { url: String, template: String },
+}
+
+fn build_notifiers(configs: &[NotifierConfig], credentials_path: &str) -> Vec<Box<dyn Notifier>> {
+    configs
+        .iter()
+        .map(|config| -> Box<dyn Notifier> {
+            match config {
+                NotifierConfig::Graph {
+                    chat_id,
+                    card_title,
+                } => Box::new(GraphNotifier {
+                    chat_id: chat_id.clone(),
+                    card_title: card_title.clone(),
+                    credentials_path: credentials_path.to_string(),
+                }),
+                NotifierConfig::Webhook { url, template } => Box::new(WebhookNotifier {
+                    url: url.clone(),
+                    template: template.clone(),
+                }),
+            }
+        })
+        .collect()
+}
+
+/// A single dataset to watch: its own endpoint and the notifier(s) it
+/// announces new files through, so one binary can serve several unrelated
+/// feeds at once.
+#[derive(Debug, Deserialize, Clone)]
+struct WatchTarget {
+    name: String,
+    endpoint: String,
+    notifiers: Vec<NotifierConfig>,
+}
+
+/// Which [`Store`] backend to archive downloaded files into, selected from
+/// config so a deployment can keep files on local disk or push them to an
+/// S3-compatible bucket without any code changes.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+enum StorageConfig {
+    File {
+        base_path: String,
+    },
+    S3 {
+        endpoint: String,
+        bucket: String,
+        region: String,
+        access_key: String,
+        secret_key: String,
+    },
+}
 
-const DB_URL: &str = "sqlite://sqlite.db";
-const SECRETS: &str = "secrets.json";
-const CREDENTIALS: &str = "credentials.json";
-const ENDPOINT: &str =
-    "https://datacenter.disaster.go.th/apiv1/apps/minisite_datacenter/203/sitedownload/10971/23149";
+fn build_store(config: &StorageConfig) -> Box<dyn Store> {
+    match config {
+        StorageConfig::File { base_path } => Box::new(FileStore::new(base_path)),
+        StorageConfig::S3 {
+            endpoint,
+            bucket,
+            region,
+            access_key,
+            secret_key,
+        } => Box::new(ObjectStore::new(endpoint, bucket, region, access_key, secret_key)),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AppConfig {
+    #[serde(default = "default_db_url")]
+    db_url: String,
+    #[serde(default = "default_secrets_path")]
+    secrets_path: String,
+    #[serde(default = "default_credentials_path")]
+    credentials_path: String,
+    /// How often `--daemon` mode polls every target, in seconds.
+    #[serde(default = "default_poll_interval_secs")]
+    poll_interval_secs: u64,
+    /// Bind address for the `--daemon` status/metrics HTTP server.
+    #[serde(default = "default_status_addr")]
+    status_addr: String,
+    /// Give up on a queued message after this many failed send attempts.
+    #[serde(default = "default_max_send_attempts")]
+    max_send_attempts: i32,
+    storage: StorageConfig,
+    targets: Vec<WatchTarget>,
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "PascalCase")]
-struct FileDetail {
+pub(crate) struct FileDetail {
     #[serde(rename = "ID")]
-    id: String,
-    subject: String,
-    link_download: String,
+    pub(crate) id: String,
+    pub(crate) subject: String,
+    pub(crate) link_download: String,
+    /// Bytes downloaded from `link_download`, attached to outbound
+    /// notifications; never present in the upstream JSON payload.
+    #[serde(skip)]
+    pub(crate) content_blob: Vec<u8>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
-struct Credentials {
+pub(crate) struct Credentials {
     token_type: String,
     scope: String,
     expires_in: i32,
     ext_expires_in: i32,
-    access_token: String,
+    pub(crate) access_token: String,
     refresh_token: String,
     id_token: String,
+    #[serde(default)]
+    expires_at: i64,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct QueuedMessage {
+    id: i64,
+    file_id: String,
+    subject: String,
+    link_download: String,
+    target: String,
+    attempts: i32,
+    notifiers_json: String,
+    content_blob: Vec<u8>,
+    stored_key: String,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -39,11 +200,12 @@ struct Secrets {
     tenant_id: String,
     client_id: String,
     client_secret: String,
-    chat_id: String,
 }
 
 #[tokio::main]
 async fn main() {
+    dotenvy::dotenv().ok();
+
     match init_log().await {
         Ok(_) => info!("Logger initialized"),
         Err(e) => {
@@ -51,7 +213,16 @@ async fn main() {
             panic!("Error initializing logger: {}", e);
         }
     };
-    let db = match init_db().await {
+
+    let config = match read_config().await {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Error reading config: {}", e);
+            panic!("Error reading config: {}", e);
+        }
+    };
+
+    let db = match init_db(&config.db_url).await {
         Ok(db) => db,
         Err(e) => {
             error!("Error initializing database: {}", e);
@@ -66,38 +237,121 @@ async fn main() {
             panic!("Error migrating database: {}", e);
         }
     }
-    let file_list = match fetch_json().await {
-        Ok(file_list) => file_list,
-        Err(e) => {
-            error!("Error fetching JSON: {}", e);
-            panic!("Error fetching JSON: {}", e);
+
+    let status = Arc::new(RwLock::new(RunStatus::default()));
+    let metrics = Arc::new(Metrics::new());
+
+    if std::env::args().any(|arg| arg == "--daemon") {
+        let state = AppState {
+            status: status.clone(),
+            metrics: metrics.clone(),
+        };
+        let status_addr = config.status_addr.clone();
+        tokio::spawn(async move {
+            if let Err(e) = status::serve(&status_addr, state).await {
+                error!("Status server error: {}", e);
+            }
+        });
+
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+            config.poll_interval_secs,
+        ));
+        loop {
+            interval.tick().await;
+            run_once(&config, &db, &status, &metrics).await;
         }
-    };
-    let new_file_list = check_new_id(file_list, &db).await;
-    if new_file_list.len() == 0 {
-        info!("No new files found");
-        std::process::exit(0);
+    } else {
+        run_once(&config, &db, &status, &metrics).await;
     }
+}
 
-    info!("New files found: {:?}", new_file_list);
+/// Runs one full poll-and-notify pass across every configured target,
+/// updating `status`/`metrics` as it goes. Shared between the single-shot
+/// (cron-driven) path and the `--daemon` scheduler loop.
+async fn run_once(
+    config: &AppConfig,
+    db: &sqlx::Pool<Sqlite>,
+    status: &Arc<RwLock<RunStatus>>,
+    metrics: &Arc<Metrics>,
+) {
+    let mut run_status = RunStatus {
+        last_run_at: now_unix(),
+        ..Default::default()
+    };
 
-    match refresh_token().await {
-        Ok(_) => info!("Token refreshed successfully"),
-        Err(e) => {
-            error!("Error refreshing token: {}", e);
-            panic!("Error refreshing token: {}", e);
+    if token_needs_refresh(config).await {
+        match refresh_token(config).await {
+            Ok(_) => {
+                info!("Token refreshed successfully");
+                metrics.token_refreshes.inc();
+            }
+            Err(e) => {
+                error!("Error refreshing token: {}", e);
+                run_status.last_error = Some(e.to_string());
+            }
         }
+    } else {
+        info!("Access token still valid, skipping refresh");
     }
 
-    for file in new_file_list {
-        match send_message(&file).await {
-            Ok(_) => info!("Message sent successfully"),
+    let store = build_store(&config.storage);
+
+    for target in &config.targets {
+        let file_list = match fetch_json(&target.endpoint).await {
+            Ok(file_list) => file_list,
             Err(e) => {
-                error!("Error sending message: {}", e);
-                panic!("Error sending message: {}", e);
+                error!("Error fetching JSON for target {}: {}", target.name, e);
+                run_status.last_error = Some(e.to_string());
+                continue;
             }
+        };
+        metrics.files_fetched.inc_by(file_list.len() as u64);
+        run_status.files_seen += file_list.len() as u64;
+
+        let new_file_list = match check_new_id(file_list, db, &target.name).await {
+            Ok(new_file_list) => new_file_list,
+            Err(e) => {
+                error!("Error checking new files for target {}: {}", target.name, e);
+                run_status.last_error = Some(e.to_string());
+                continue;
+            }
+        };
+        if new_file_list.len() == 0 {
+            info!("No new files found for target {}", target.name);
+            continue;
+        }
+
+        metrics.files_new.inc_by(new_file_list.len() as u64);
+        run_status.files_new += new_file_list.len() as u64;
+        info!("New files found for target {}: {:?}", target.name, new_file_list);
+
+        for file in new_file_list {
+            // The file is already recorded as seen in `content`; queue it for
+            // delivery right away so a transient download failure later is
+            // retried from the send queue rather than silently dropped.
+            match enqueue_message(db, &file, target).await {
+                Ok(_) => info!("Queued message for file {}", file.id),
+                Err(e) => {
+                    error!("Error queueing message: {}", e);
+                    run_status.last_error = Some(e.to_string());
+                }
+            }
+        }
+    }
+
+    match drain_send_queue(db, config, metrics, store.as_ref()).await {
+        Ok((sent, failed)) => {
+            info!("Send queue drained");
+            run_status.messages_sent = sent;
+            run_status.send_failures = failed;
+        }
+        Err(e) => {
+            error!("Error draining send queue: {}", e);
+            run_status.last_error = Some(e.to_string());
         }
     }
+
+    *status.write().await = run_status;
 }
 
 async fn init_log() -> Result<(), SetLoggerError> {
@@ -116,10 +370,10 @@ async fn init_log() -> Result<(), SetLoggerError> {
     ])
 }
 
-async fn init_db() -> Result<sqlx::Pool<Sqlite>, sqlx::Error> {
-    if !Sqlite::database_exists(DB_URL).await.unwrap_or(false) {
-        info!("Database does not exist, creating... {}", DB_URL);
-        match Sqlite::create_database(DB_URL).await {
+async fn init_db(db_url: &str) -> Result<sqlx::Pool<Sqlite>, sqlx::Error> {
+    if !Sqlite::database_exists(db_url).await.unwrap_or(false) {
+        info!("Database does not exist, creating... {}", db_url);
+        match Sqlite::create_database(db_url).await {
             Ok(_) => info!("Database created successfully"),
             Err(e) => {
                 error!("Error creating database: {}", e);
@@ -130,7 +384,7 @@ async fn init_db() -> Result<sqlx::Pool<Sqlite>, sqlx::Error> {
         info!("Database already exists");
     }
 
-    let db = SqlitePool::connect(DB_URL).await;
+    let db = SqlitePool::connect(db_url).await;
 
     match db {
         Ok(db) => {
@@ -159,53 +413,129 @@ async fn migrate_db(db: &sqlx::Pool<Sqlite>) -> Result<(), sqlx::migrate::Migrat
     }
 }
 
-async fn read_secrets() -> Result<Secrets, std::io::Error> {
-    let file = File::open(SECRETS).expect("No secrets file found");
+/// Reads `config.json`, letting well-known environment variables (optionally
+/// loaded from a `.env` file via `dotenvy`) override individual fields. This
+/// is how a deployment can swap the DB path or credentials location without
+/// touching the checked-in config.
+async fn read_config() -> Result<AppConfig, std::io::Error> {
+    let file = File::open(CONFIG_PATH).expect("No config file found");
+    let json: serde_json::Value =
+        serde_json::from_reader(file).expect("Error reading config file");
+    let mut config: AppConfig = serde_json::from_value(json).expect("Cannot parse JSON");
+
+    if let Ok(db_url) = std::env::var("DB_URL") {
+        config.db_url = db_url;
+    }
+    if let Ok(secrets_path) = std::env::var("SECRETS_PATH") {
+        config.secrets_path = secrets_path;
+    }
+    if let Ok(credentials_path) = std::env::var("CREDENTIALS_PATH") {
+        config.credentials_path = credentials_path;
+    }
+
+    Ok(config)
+}
+
+async fn read_secrets(secrets_path: &str) -> Result<Secrets, std::io::Error> {
+    let file = File::open(secrets_path).expect("No secrets file found");
     let json: serde_json::Value =
         serde_json::from_reader(file).expect("Error reading secrets file");
     let secrets: Secrets = serde_json::from_value(json).expect("Cannot parse JSON");
     Ok(secrets)
 }
 
-async fn read_credentials() -> Result<Credentials, std::io::Error> {
-    let file = File::open(CREDENTIALS).expect("No credentials file found");
+pub(crate) async fn read_credentials(credentials_path: &str) -> Result<Credentials, std::io::Error> {
+    let file = File::open(credentials_path).expect("No credentials file found");
     let json: serde_json::Value =
         serde_json::from_reader(file).expect("Error reading credentials file");
     let creds: Credentials = serde_json::from_value(json).expect("Cannot parse JSON");
     Ok(creds)
 }
 
-async fn fetch_json() -> Result<Vec<FileDetail>, reqwest::Error> {
+/// Error surfaced by [`fetch_json`]: either the HTTP request itself failed,
+/// or the upstream response didn't have the shape we expect.
+#[derive(Debug)]
+enum FetchError {
+    Request(reqwest::Error),
+    Parse(serde_json::Error),
+    Shape(&'static str),
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Request(e) => write!(f, "fetch request error: {}", e),
+            FetchError::Parse(e) => write!(f, "fetch response parse error: {}", e),
+            FetchError::Shape(msg) => write!(f, "unexpected fetch response shape: {}", msg),
+        }
+    }
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(e: reqwest::Error) -> Self {
+        FetchError::Request(e)
+    }
+}
+
+impl From<serde_json::Error> for FetchError {
+    fn from(e: serde_json::Error) -> Self {
+        FetchError::Parse(e)
+    }
+}
+
+async fn fetch_json(endpoint: &str) -> Result<Vec<FileDetail>, FetchError> {
     let client = reqwest::Client::new();
-    let res = client.get(ENDPOINT).send().await?;
+    let res = client.get(endpoint).send().await?;
     let body = res.text().await?;
-    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
-    let data = json.get("data").expect("No Data");
-    let file_list: Vec<FileDetail> =
-        serde_json::from_value(data.get("file_list").expect("No File List").clone())
-            .expect("Cannot parse JSON");
+    let json: serde_json::Value = serde_json::from_str(&body)?;
+    let data = json
+        .get("data")
+        .ok_or(FetchError::Shape("missing \"data\" field"))?;
+    let file_list_json = data
+        .get("file_list")
+        .ok_or(FetchError::Shape("missing \"file_list\" field"))?;
+    let file_list: Vec<FileDetail> = serde_json::from_value(file_list_json.clone())?;
     debug!("{:?}", file_list);
     Ok(file_list)
 }
 
-async fn check_new_id(file_list: Vec<FileDetail>, pool: &sqlx::Pool<Sqlite>) -> Vec<FileDetail> {
+async fn download_file(url: &str) -> Result<Vec<u8>, reqwest::Error> {
+    let client = reqwest::Client::new();
+    let bytes = client.get(url).send().await?.bytes().await?;
+    Ok(bytes.to_vec())
+}
+
+/// Derives a content-addressed storage key from a SHA-256 digest of the
+/// file's bytes, so identical uploads always land on the same key.
+fn content_key(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    format!("{:x}", hasher.finalize())
+}
+
+async fn check_new_id(
+    file_list: Vec<FileDetail>,
+    pool: &sqlx::Pool<Sqlite>,
+    target: &str,
+) -> Result<Vec<FileDetail>, sqlx::Error> {
     let mut new_files: Vec<FileDetail> = Vec::new();
     for file in file_list {
         let id = file.id.clone();
-        let exists = query_by_id(pool, &id).await.unwrap();
+        let exists = query_by_id(pool, &id, target).await?;
         if !exists {
             info!("New file found: {}", id);
-            let _ = insert_file(pool, &file).await;
+            let _ = insert_file(pool, &file, target).await;
             new_files.push(file);
         }
     }
     new_files.reverse();
-    return new_files;
+    Ok(new_files)
 }
 
-async fn query_by_id(pool: &sqlx::Pool<Sqlite>, id: &str) -> Result<bool, sqlx::Error> {
-    let result = sqlx::query("SELECT * FROM content WHERE id = ?")
+async fn query_by_id(pool: &sqlx::Pool<Sqlite>, id: &str, target: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("SELECT * FROM content WHERE id = ? AND target = ?")
         .bind(id.parse::<i32>().unwrap_or_else(|_| 0i32))
+        .bind(target)
         .fetch_optional(pool)
         .await;
     match result {
@@ -217,22 +547,284 @@ async fn query_by_id(pool: &sqlx::Pool<Sqlite>, id: &str) -> Result<bool, sqlx::
     }
 }
 
-async fn insert_file(pool: &sqlx::Pool<Sqlite>, file: &FileDetail) -> Result<(), sqlx::Error> {
-    let result = sqlx::query("INSERT INTO content (id, subject, linkDownload) VALUES (?, ?, ?)")
-        .bind(&file.id.parse::<i32>().unwrap_or_else(|_| 0i32))
-        .bind(&file.subject)
-        .bind(&file.link_download)
-        .execute(pool)
-        .await;
+async fn insert_file(
+    pool: &sqlx::Pool<Sqlite>,
+    file: &FileDetail,
+    target: &str,
+) -> Result<(), sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT INTO content (id, subject, linkDownload, target) VALUES (?, ?, ?, ?)",
+    )
+    .bind(&file.id.parse::<i32>().unwrap_or_else(|_| 0i32))
+    .bind(&file.subject)
+    .bind(&file.link_download)
+    .bind(target)
+    .execute(pool)
+    .await;
     match result {
         Ok(_) => Ok(()),
         Err(e) => Err(e),
     }
 }
 
-async fn refresh_token() -> Result<(), reqwest::Error> {
-    let secrets = read_secrets().await.unwrap();
-    let creds = read_credentials().await.unwrap();
+async fn set_stored_key(
+    pool: &sqlx::Pool<Sqlite>,
+    id: &str,
+    target: &str,
+    key: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE content SET stored_key = ? WHERE id = ? AND target = ?")
+        .bind(key)
+        .bind(id.parse::<i32>().unwrap_or_else(|_| 0i32))
+        .bind(target)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+async fn enqueue_message(
+    pool: &sqlx::Pool<Sqlite>,
+    file: &FileDetail,
+    target: &WatchTarget,
+) -> Result<(), sqlx::Error> {
+    let notifiers_json = serde_json::to_string(&target.notifiers).unwrap_or_else(|_| "[]".to_string());
+    sqlx::query(
+        "INSERT INTO send_queue (file_id, subject, link_download, target, attempts, next_attempt_at, notifiers_json) VALUES (?, ?, ?, ?, 0, ?, ?)",
+    )
+    .bind(&file.id)
+    .bind(&file.subject)
+    .bind(&file.link_download)
+    .bind(&target.name)
+    .bind(now_unix())
+    .bind(notifiers_json)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Handles a failed attempt: reschedules the message with exponential
+/// backoff, or deletes it once `attempts` reaches `max_attempts`.
+/// `notifiers_json` is passed when only a subset of notifiers still needs
+/// retrying; omit it to leave the column untouched (e.g. a download retry
+/// that never got as far as notifying anyone).
+async fn reschedule_or_give_up(
+    pool: &sqlx::Pool<Sqlite>,
+    message_id: i64,
+    file_id: &str,
+    attempts: i32,
+    max_attempts: i32,
+    error: &str,
+    notifiers_json: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    if attempts >= max_attempts {
+        error!(
+            "Giving up on file {} after {} attempts",
+            file_id, attempts
+        );
+        sqlx::query("DELETE FROM send_queue WHERE id = ?")
+            .bind(message_id)
+            .execute(pool)
+            .await?;
+        return Ok(());
+    }
+
+    let backoff = SEND_RETRY_BASE_SECS * 2i64.pow(attempts as u32);
+    let next_attempt_at = now_unix() + backoff.min(SEND_RETRY_MAX_SECS);
+    match notifiers_json {
+        Some(notifiers_json) => {
+            sqlx::query(
+                "UPDATE send_queue SET attempts = ?, next_attempt_at = ?, last_error = ?, notifiers_json = ? WHERE id = ?",
+            )
+            .bind(attempts)
+            .bind(next_attempt_at)
+            .bind(error)
+            .bind(notifiers_json)
+            .bind(message_id)
+            .execute(pool)
+            .await?;
+        }
+        None => {
+            sqlx::query(
+                "UPDATE send_queue SET attempts = ?, next_attempt_at = ?, last_error = ? WHERE id = ?",
+            )
+            .bind(attempts)
+            .bind(next_attempt_at)
+            .bind(error)
+            .bind(message_id)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Sends every due message in `send_queue`, deleting it on success and
+/// rescheduling it with exponential backoff on failure. Messages that reach
+/// `config.max_send_attempts` are logged and dropped instead of retried forever.
+/// A message queued before its file was downloaded is downloaded and stored
+/// here instead, so a transient download failure is retried like any other
+/// send failure rather than dropping the file the moment it's first seen.
+async fn drain_send_queue(
+    pool: &sqlx::Pool<Sqlite>,
+    config: &AppConfig,
+    metrics: &Metrics,
+    store: &dyn Store,
+) -> Result<(u64, u64), sqlx::Error> {
+    let due: Vec<QueuedMessage> = sqlx::query_as(
+        "SELECT id, file_id, subject, link_download, target, attempts, notifiers_json, content_blob, stored_key FROM send_queue WHERE next_attempt_at <= ?",
+    )
+    .bind(now_unix())
+    .fetch_all(pool)
+    .await?;
+
+    let mut sent = 0u64;
+    let mut failed = 0u64;
+
+    for message in due {
+        let mut content_blob = message.content_blob.clone();
+
+        if message.stored_key.is_empty() {
+            let bytes = match download_file(&message.link_download).await {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    metrics.send_failures.inc();
+                    failed += 1;
+                    let attempts = message.attempts + 1;
+                    error!(
+                        "Error downloading file {} (attempt {}): {}",
+                        message.file_id, attempts, e
+                    );
+                    reschedule_or_give_up(
+                        pool,
+                        message.id,
+                        &message.file_id,
+                        attempts,
+                        config.max_send_attempts,
+                        &e.to_string(),
+                        None,
+                    )
+                    .await?;
+                    continue;
+                }
+            };
+
+            let key = content_key(&bytes);
+            if let Err(e) = store.put(&key, &bytes).await {
+                metrics.send_failures.inc();
+                failed += 1;
+                let attempts = message.attempts + 1;
+                error!(
+                    "Error storing file {} (attempt {}): {}",
+                    message.file_id, attempts, e
+                );
+                reschedule_or_give_up(
+                    pool,
+                    message.id,
+                    &message.file_id,
+                    attempts,
+                    config.max_send_attempts,
+                    &e.to_string(),
+                    None,
+                )
+                .await?;
+                continue;
+            }
+            if let Err(e) = set_stored_key(pool, &message.file_id, &message.target, &key).await {
+                error!(
+                    "Error recording stored key for file {}: {}",
+                    message.file_id, e
+                );
+            }
+            sqlx::query("UPDATE send_queue SET content_blob = ?, stored_key = ? WHERE id = ?")
+                .bind(&bytes)
+                .bind(&key)
+                .bind(message.id)
+                .execute(pool)
+                .await?;
+            content_blob = bytes;
+        }
+
+        let file_detail = FileDetail {
+            id: message.file_id.clone(),
+            subject: message.subject.clone(),
+            link_download: message.link_download.clone(),
+            content_blob,
+        };
+        let notifier_configs: Vec<NotifierConfig> =
+            serde_json::from_str(&message.notifiers_json).unwrap_or_default();
+        let notifiers = build_notifiers(&notifier_configs, &config.credentials_path);
+
+        // Only configs that are still failing get retried; a notifier that
+        // already succeeded must not fire again on the next attempt.
+        let mut remaining_configs = Vec::new();
+        let mut last_error = None;
+        for (notifier_config, notifier) in notifier_configs.iter().zip(notifiers.iter()) {
+            if let Err(e) = notifier.notify(&file_detail).await {
+                last_error = Some(e.to_string());
+                remaining_configs.push(notifier_config.clone());
+            }
+        }
+
+        match last_error {
+            None => {
+                info!("Message sent successfully for file {}", message.file_id);
+                metrics.messages_sent.inc();
+                sent += 1;
+                sqlx::query("DELETE FROM send_queue WHERE id = ?")
+                    .bind(message.id)
+                    .execute(pool)
+                    .await?;
+            }
+            Some(e) => {
+                metrics.send_failures.inc();
+                failed += 1;
+                let attempts = message.attempts + 1;
+                error!(
+                    "Error sending message for file {} (attempt {}): {}",
+                    message.file_id, attempts, e
+                );
+                let remaining_json = serde_json::to_string(&remaining_configs)
+                    .unwrap_or_else(|_| "[]".to_string());
+                reschedule_or_give_up(
+                    pool,
+                    message.id,
+                    &message.file_id,
+                    attempts,
+                    config.max_send_attempts,
+                    &e,
+                    Some(&remaining_json),
+                )
+                .await?;
+            }
+        }
+    }
+    Ok((sent, failed))
+}
+
+/// Returns `true` when the stored credentials have no recorded expiry
+/// (`expires_at` is zero) or that expiry has passed, so the caller should call
+/// `refresh_token()` before using the access token.
+async fn token_needs_refresh(config: &AppConfig) -> bool {
+    let creds = match read_credentials(&config.credentials_path).await {
+        Ok(creds) => creds,
+        Err(_) => return true,
+    };
+    if creds.expires_at == 0 {
+        return true;
+    }
+    now_unix() >= creds.expires_at
+}
+
+pub(crate) fn now_unix() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time is before the Unix epoch")
+        .as_secs() as i64
+}
+
+async fn refresh_token(config: &AppConfig) -> Result<(), reqwest::Error> {
+    let secrets = read_secrets(&config.secrets_path).await.unwrap();
+    let creds = read_credentials(&config.credentials_path).await.unwrap();
     let client = reqwest::Client::new();
     let res = client
         .post(format!(
@@ -250,52 +842,13 @@ async fn refresh_token() -> Result<(), reqwest::Error> {
         .await?;
     let body = res.text().await?;
     let json: serde_json::Value = serde_json::from_str(&body).unwrap();
-    let creds: Credentials = serde_json::from_value(json).unwrap();
-    let file = File::create(CREDENTIALS).unwrap();
+    let mut creds: Credentials = serde_json::from_value(json).unwrap();
+    creds.expires_at = now_unix() + creds.expires_in as i64 - TOKEN_EXPIRY_BUFFER_SECS;
+    let file = File::create(&config.credentials_path).unwrap();
     serde_json::to_writer(file, &creds).unwrap();
     Ok(())
 }
 
-async fn send_message(file_detail: &FileDetail) -> Result<(), reqwest::Error> {
-    let secrets = read_secrets().await.unwrap();
-    let creds = read_credentials().await.unwrap();
-    let client = reqwest::Client::new();
-    let attachment_id = uuid::Uuid::new_v4().to_string();
-    let res = client
-        .post(format!(
-            "https://graph.microsoft.com/v1.0/chats/{}/messages",
-            &secrets.chat_id
-        ))
-        .header("Authorization", format!("Bearer {}", &creds.access_token))
-        .json(&serde_json::json!({
-            "body": {
-                "content": format!("<attachment id=\"{}\"></attachment>", &attachment_id),
-                "contentType": "html"
-            },
-            "attachments": [
-                {
-                    "id": &attachment_id,
-                    "contentType": "application/vnd.microsoft.card.thumbnail",
-                    "contentUrl": file_detail.link_download,
-                    "name": file_detail.subject,
-                    "content": serde_json::json!({
-                        "title": "[New] การประกาศเขตอุทกภัย",
-                        "subtitle": file_detail.subject,
-                        "text": "Click the link below to download the file",
-                        "buttons": [
-                            {
-                                "type": "openUrl",
-                                "title": "Download",
-                                "value": file_detail.link_download
-                            }
-                        ]
-                    }).to_string()
-                }
-            ]
-        }))
-        .send()
-        .await?;
-    let body = res.text().await?;
-    info!("{}", body);
-    Ok(())
+pub(crate) fn to_base64(bytes: &[u8]) -> String {
+    BASE64.encode(bytes)
 }
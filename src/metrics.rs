@@ -0,0 +1,69 @@
+use prometheus::{Encoder, IntCounter, Registry, TextEncoder};
+
+/// Prometheus counters exposed on `/metrics` when running with `--daemon`.
+pub struct Metrics {
+    registry: Registry,
+    pub files_fetched: IntCounter,
+    pub files_new: IntCounter,
+    pub messages_sent: IntCounter,
+    pub send_failures: IntCounter,
+    pub token_refreshes: IntCounter,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+
+        let files_fetched = IntCounter::new(
+            "flood_crawl_files_fetched_total",
+            "Total files fetched from upstream feeds",
+        )
+        .unwrap();
+        let files_new = IntCounter::new(
+            "flood_crawl_files_new_total",
+            "Total new files discovered",
+        )
+        .unwrap();
+        let messages_sent = IntCounter::new(
+            "flood_crawl_messages_sent_total",
+            "Total notifications sent successfully",
+        )
+        .unwrap();
+        let send_failures = IntCounter::new(
+            "flood_crawl_send_failures_total",
+            "Total notification send failures",
+        )
+        .unwrap();
+        let token_refreshes = IntCounter::new(
+            "flood_crawl_token_refreshes_total",
+            "Total OAuth token refreshes performed",
+        )
+        .unwrap();
+
+        registry.register(Box::new(files_fetched.clone())).unwrap();
+        registry.register(Box::new(files_new.clone())).unwrap();
+        registry.register(Box::new(messages_sent.clone())).unwrap();
+        registry.register(Box::new(send_failures.clone())).unwrap();
+        registry
+            .register(Box::new(token_refreshes.clone()))
+            .unwrap();
+
+        Metrics {
+            registry,
+            files_fetched,
+            files_new,
+            messages_sent,
+            send_failures,
+            token_refreshes,
+        }
+    }
+
+    /// Renders the current counters in Prometheus text exposition format.
+    pub fn encode(&self) -> String {
+        let encoder = TextEncoder::new();
+        let metric_families = self.registry.gather();
+        let mut buffer = Vec::new();
+        encoder.encode(&metric_families, &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
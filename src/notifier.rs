@@ -0,0 +1,144 @@
+use async_trait::async_trait;
+use std::fmt;
+
+use crate::{read_credentials, FileDetail};
+
+/// Error surfaced by a [`Notifier`] implementation.
+#[derive(Debug)]
+pub enum NotifierError {
+    Request(reqwest::Error),
+    Serialize(serde_json::Error),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for NotifierError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotifierError::Request(e) => write!(f, "notifier request error: {}", e),
+            NotifierError::Serialize(e) => write!(f, "notifier template error: {}", e),
+            NotifierError::Io(e) => write!(f, "notifier credentials error: {}", e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for NotifierError {
+    fn from(e: reqwest::Error) -> Self {
+        NotifierError::Request(e)
+    }
+}
+
+impl From<serde_json::Error> for NotifierError {
+    fn from(e: serde_json::Error) -> Self {
+        NotifierError::Serialize(e)
+    }
+}
+
+impl From<std::io::Error> for NotifierError {
+    fn from(e: std::io::Error) -> Self {
+        NotifierError::Io(e)
+    }
+}
+
+/// Delivers a new file announcement somewhere. `GraphNotifier` posts a
+/// Microsoft Teams thumbnail card; `WebhookNotifier` POSTs an arbitrary JSON
+/// template, so a site can run either or both.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, file: &FileDetail) -> Result<(), NotifierError>;
+}
+
+/// Posts the announcement as a Microsoft Graph chat message, carrying the
+/// downloaded file as hosted content in addition to the thumbnail card.
+pub struct GraphNotifier {
+    pub chat_id: String,
+    pub card_title: String,
+    pub credentials_path: String,
+}
+
+#[async_trait]
+impl Notifier for GraphNotifier {
+    async fn notify(&self, file: &FileDetail) -> Result<(), NotifierError> {
+        let creds = read_credentials(&self.credentials_path).await?;
+        let client = reqwest::Client::new();
+        let attachment_id = uuid::Uuid::new_v4().to_string();
+        let res = client
+            .post(format!(
+                "https://graph.microsoft.com/v1.0/chats/{}/messages",
+                &self.chat_id
+            ))
+            .header("Authorization", format!("Bearer {}", &creds.access_token))
+            .json(&serde_json::json!({
+                "body": {
+                    "content": format!("<attachment id=\"{}\"></attachment>", &attachment_id),
+                    "contentType": "html"
+                },
+                "attachments": [
+                    {
+                        "id": &attachment_id,
+                        "contentType": "application/vnd.microsoft.card.thumbnail",
+                        "contentUrl": file.link_download,
+                        "name": file.subject,
+                        "content": serde_json::json!({
+                            "title": self.card_title,
+                            "subtitle": file.subject,
+                            "text": "Click the link below to download the file",
+                            "buttons": [
+                                {
+                                    "type": "openUrl",
+                                    "title": "Download",
+                                    "value": file.link_download
+                                }
+                            ]
+                        }).to_string()
+                    }
+                ],
+                "hostedContents": [
+                    {
+                        "@microsoft.graph.temporaryId": &attachment_id,
+                        "contentBytes": crate::to_base64(&file.content_blob),
+                        "contentType": "application/octet-stream"
+                    }
+                ]
+            }))
+            .send()
+            .await?;
+        let body = res.text().await?;
+        info!("{}", body);
+        Ok(())
+    }
+}
+
+/// POSTs a configurable JSON template to an arbitrary URL, with `{subject}`
+/// and `{link}` placeholders substituted from the file being announced.
+/// Suitable for Slack/Discord/Teams incoming webhooks or a custom endpoint.
+pub struct WebhookNotifier {
+    pub url: String,
+    pub template: String,
+}
+
+/// Escapes `value` the way `serde_json` would inside a string literal,
+/// without the surrounding quotes, so it can be substituted into a
+/// template's existing `"..."` placeholder.
+fn json_escape(value: &str) -> String {
+    let quoted = serde_json::to_string(value).unwrap_or_default();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify(&self, file: &FileDetail) -> Result<(), NotifierError> {
+        let body = self
+            .template
+            .replace("{subject}", &json_escape(&file.subject))
+            .replace("{link}", &json_escape(&file.link_download));
+        let payload: serde_json::Value = serde_json::from_str(&body)?;
+        let client = reqwest::Client::new();
+        client
+            .post(&self.url)
+            .json(&payload)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
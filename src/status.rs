@@ -0,0 +1,49 @@
+use axum::{extract::State, routing::get, Json, Router};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::metrics::Metrics;
+
+/// A snapshot of the last completed run, served from `/status`.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RunStatus {
+    pub last_run_at: i64,
+    pub files_seen: u64,
+    pub files_new: u64,
+    pub messages_sent: u64,
+    pub send_failures: u64,
+    pub last_error: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct AppState {
+    pub status: Arc<RwLock<RunStatus>>,
+    pub metrics: Arc<Metrics>,
+}
+
+async fn healthz() -> &'static str {
+    "OK"
+}
+
+async fn status_handler(State(state): State<AppState>) -> Json<RunStatus> {
+    Json(state.status.read().await.clone())
+}
+
+async fn metrics_handler(State(state): State<AppState>) -> String {
+    state.metrics.encode()
+}
+
+/// Serves `/healthz`, `/status`, and `/metrics` on `addr` until the process
+/// exits, giving a `--daemon` run basic liveness and observability.
+pub async fn serve(addr: &str, state: AppState) -> Result<(), std::io::Error> {
+    let app = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/status", get(status_handler))
+        .route("/metrics", get(metrics_handler))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("Status server listening on {}", addr);
+    axum::serve(listener, app).await
+}
@@ -0,0 +1,183 @@
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::PathBuf;
+use time::OffsetDateTime;
+
+/// Error surfaced by a [`Store`] implementation.
+#[derive(Debug)]
+pub enum StoreError {
+    Io(std::io::Error),
+    Request(reqwest::Error),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Io(e) => write!(f, "storage I/O error: {}", e),
+            StoreError::Request(e) => write!(f, "storage request error: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for StoreError {
+    fn from(e: std::io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+impl From<reqwest::Error> for StoreError {
+    fn from(e: reqwest::Error) -> Self {
+        StoreError::Request(e)
+    }
+}
+
+#[async_trait]
+pub trait Store: Send + Sync {
+    /// Persists `bytes` under `key`, overwriting any existing blob.
+    async fn put(&self, key: &str, bytes: &[u8]) -> Result<(), StoreError>;
+}
+
+/// Stores blobs as plain files under `base_path/<key>`.
+pub struct FileStore {
+    base_path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(base_path: impl Into<PathBuf>) -> Self {
+        FileStore {
+            base_path: base_path.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Store for FileStore {
+    async fn put(&self, key: &str, bytes: &[u8]) -> Result<(), StoreError> {
+        std::fs::create_dir_all(&self.base_path)?;
+        std::fs::write(self.base_path.join(key), bytes)?;
+        Ok(())
+    }
+}
+
+/// Stores blobs in an S3-compatible bucket via a SigV4-signed `PUT` request.
+pub struct ObjectStore {
+    endpoint: String,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts a key of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    format!("{:x}", hasher.finalize())
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl ObjectStore {
+    pub fn new(
+        endpoint: impl Into<String>,
+        bucket: impl Into<String>,
+        region: impl Into<String>,
+        access_key: impl Into<String>,
+        secret_key: impl Into<String>,
+    ) -> Self {
+        ObjectStore {
+            endpoint: endpoint.into(),
+            bucket: bucket.into(),
+            region: region.into(),
+            access_key: access_key.into(),
+            secret_key: secret_key.into(),
+        }
+    }
+
+    /// Signs a `PUT {uri}` request against this bucket per AWS Signature
+    /// Version 4, returning the `x-amz-date`, `x-amz-content-sha256`, and
+    /// `Authorization` header values to attach to it.
+    fn sign_put(&self, host: &str, uri: &str, bytes: &[u8]) -> (String, String, String) {
+        let now = OffsetDateTime::from_unix_timestamp(crate::now_unix())
+            .expect("current time is a valid timestamp");
+        let amz_date = format!(
+            "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+            now.year(),
+            now.month() as u8,
+            now.day(),
+            now.hour(),
+            now.minute(),
+            now.second()
+        );
+        let date_stamp = &amz_date[..8];
+        let payload_hash = sha256_hex(bytes);
+
+        let canonical_headers = format!(
+            "host:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n",
+            host, payload_hash, amz_date
+        );
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "PUT\n{}\n\n{}\n{}\n{}",
+            uri, canonical_headers, signed_headers, payload_hash
+        );
+
+        let credential_scope = format!("{}/{}/s3/aws4_request", date_stamp, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            credential_scope,
+            sha256_hex(canonical_request.as_bytes())
+        );
+
+        let k_date = hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), date_stamp);
+        let k_region = hmac_sha256(&k_date, &self.region);
+        let k_service = hmac_sha256(&k_region, "s3");
+        let k_signing = hmac_sha256(&k_service, "aws4_request");
+        let signature = hex_encode(&hmac_sha256(&k_signing, &string_to_sign));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, credential_scope, signed_headers, signature
+        );
+
+        (amz_date, payload_hash, authorization)
+    }
+}
+
+#[async_trait]
+impl Store for ObjectStore {
+    async fn put(&self, key: &str, bytes: &[u8]) -> Result<(), StoreError> {
+        let url = format!("{}/{}/{}", self.endpoint, self.bucket, key);
+        let host = reqwest::Url::parse(&url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+            .unwrap_or_default();
+        let uri = format!("/{}/{}", self.bucket, key);
+        let (amz_date, payload_hash, authorization) = self.sign_put(&host, &uri, bytes);
+
+        let client = reqwest::Client::new();
+        client
+            .put(url)
+            .header("host", host)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("authorization", authorization)
+            .body(bytes.to_vec())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}